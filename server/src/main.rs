@@ -1,28 +1,97 @@
 // modified
 use axum::{
-    extract::{Query, State},
-    http::StatusCode,
-    routing::get,
+    extract::{Path as RoutePath, Query, Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{sse::{Event, Sse}, Response},
+    routing::{get, post},
     Json, Router,
 };
+use futures::stream::{Stream, StreamExt};
+use grep_matcher::Matcher;
+use grep_regex::{RegexMatcher, RegexMatcherBuilder};
+use grep_searcher::{Searcher, Sink, SinkMatch};
+use ignore::{WalkBuilder, WalkState};
+use notify::{RecursiveMode, Watcher};
+use notify_debouncer_full::{new_debouncer, DebouncedEvent};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, HashMap},
+    convert::Infallible,
     net::SocketAddr,
     path::{Path, PathBuf},
-    sync::{Arc, RwLock},
-    time::{SystemTime, UNIX_EPOCH},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    process::{ChildStdin, Command},
+    sync::{broadcast, oneshot, Mutex as AsyncMutex, OnceCell},
+    time::timeout,
+};
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_util::sync::CancellationToken;
+use tower_http::{
+    cors::{AllowOrigin, CorsLayer},
+    services::ServeDir,
 };
-use tower_http::{cors::CorsLayer, services::ServeDir};
-use walkdir::WalkDir;
 
 // --- State & Types ---
 
+/// A language server's spawn-and-initialize result, resolved at most once.
+type LspServerCell = Arc<OnceCell<Arc<LspServer>>>;
+
+/// Kicks off a search's walk; boxed so `start_search` can stash it away and
+/// `stream_search` can run it once the first subscriber is listening.
+type StartSearch = Box<dyn FnOnce() + Send>;
+
+/// A pending or in-progress search's result fan-out, keyed by search_id.
+///
+/// `start` holds the walk itself, not yet spawned: `broadcast::Sender`
+/// receivers never see anything sent before they subscribed, so starting the
+/// walk in `start_search` (before the client's `GET /api/search/stream`
+/// round trip even happens) would silently drop every result found in that
+/// gap. Stashing the walk here and running it from `stream_search`, after
+/// subscribing, guarantees no result is ever published before a receiver
+/// exists to see it.
+struct SearchEntry {
+    tx: broadcast::Sender<SearchResult>,
+    start: std::sync::Mutex<Option<StartSearch>>,
+}
+
 #[derive(Clone)]
 struct AppState {
     repo_root: PathBuf,
     checklist_path: PathBuf,
     checklist: Arc<RwLock<BTreeMap<String, ChecklistItem>>>,
+    searches: Arc<RwLock<HashMap<String, CancellationToken>>>,
+    // Fan-out for each in-progress search's results, keyed by search_id. A
+    // `broadcast::Sender` (rather than the single-consumer `mpsc::Receiver`
+    // this started out as) means `/api/search/stream` can be reconnected to
+    // mid-scan - e.g. after the network hiccup that `EventSource` silently
+    // retries on - without losing the rest of the results, and a search with
+    // no connected client yet never blocks the walker thread.
+    search_streams: Arc<RwLock<HashMap<String, Arc<SearchEntry>>>>,
+    // Extra path components to always exclude from search/walks, on top of
+    // whatever .gitignore/.ignore rules apply. Lets a deployment add custom
+    // excludes (e.g. a vendored directory) without recompiling.
+    extra_ignores: Arc<Vec<String>>,
+    // Fan-out for filesystem change notifications; `watch_events` subscribes
+    // a fresh receiver per connected client.
+    change_tx: broadcast::Sender<ChangeEvent>,
+    // Which binary (+ args) to launch per language id, read from
+    // codeedit/lsp.json. Absent entries mean no LSP support for that language.
+    lsp_config: Arc<HashMap<String, LspServerConfig>>,
+    // Lazily spawned language servers, keyed by language id. Each value is a
+    // cell that's resolved at most once, so spawning a slow server for one
+    // language doesn't hold up lookups for any other.
+    lsp_servers: Arc<AsyncMutex<HashMap<String, LspServerCell>>>,
+    // Bounds how much of a single archive entry search will decompress, so a
+    // crafted zip bomb can't be used to exhaust memory.
+    max_archive_entry_bytes: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,7 +101,7 @@ struct ChecklistItem {
     updated_ts: u64,
 }
 
-#[derive(Debug, Serialize, PartialEq)]
+#[derive(Debug, Serialize, Clone, PartialEq)]
 struct SearchResult {
     file: String,
     line: usize,
@@ -41,10 +110,29 @@ struct SearchResult {
 }
 
 #[derive(Deserialize)]
-struct SearchParams {
+struct SearchStartParams {
     q: String,
     regex: Option<bool>,
     glob: Option<String>,
+    respect_gitignore: Option<bool>,
+    include_hidden: Option<bool>,
+}
+
+#[derive(Serialize)]
+struct SearchStartResponse {
+    search_id: String,
+}
+
+#[derive(Deserialize)]
+struct SearchIdParams {
+    id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ChangeEvent {
+    kind: String, // "created" | "modified" | "removed"
+    path: String,
+    etag: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -72,87 +160,519 @@ struct PatchChecklist {
     note: Option<String>,
 }
 
-// --- Logic (Decoupled from Axum for testing) ---
+#[derive(Debug, Clone, Deserialize)]
+struct LspServerConfig {
+    command: String,
+    #[serde(default)]
+    args: Vec<String>,
+}
 
-fn perform_search(root: &Path, query: &str, use_regex: bool, glob: Option<&str>) -> Vec<SearchResult> {
-    let mut results = Vec::new();
-    
-    let re = if use_regex {
-        regex::RegexBuilder::new(query).case_insensitive(true).build().ok()
-    } else { None };
-    let query_lower = query.to_ascii_lowercase();
-
-    // Normalize glob
-    let glob_pattern = glob.map(|g| g.trim_start_matches('*'));
-
-    for entry in WalkDir::new(root).into_iter().filter_map(|e| e.ok()) {
-        if !entry.file_type().is_file() { continue; }
-
-        // --- Robust Filtering ---
-        // Don't use .contains("string") on the full path, it breaks if your parent folder is named "target_app"
-        // check path components instead.
-        let components: Vec<_> = entry.path().components().map(|c| c.as_os_str().to_string_lossy()).collect();
-        if components.iter().any(|c| c == ".git" || c == "node_modules" || c == "target" || c == "dist" || c == "codeedit") {
-            continue;
-        }
+/// A running language server: one spawned child per language id, talking the
+/// standard `Content-Length`-framed JSON-RPC envelope over stdio.
+struct LspServer {
+    stdin: AsyncMutex<ChildStdin>,
+    pending: Arc<AsyncMutex<HashMap<String, oneshot::Sender<serde_json::Value>>>>,
+    diagnostics_tx: broadcast::Sender<serde_json::Value>,
+    next_id: AtomicU64,
+    // Tracks each open document's current version, keyed by URI.
+    documents: AsyncMutex<HashMap<String, u64>>,
+    // Cleared once the reader task sees the child's stdout close, so
+    // `get_or_spawn_lsp` can tell a cached server apart from a dead one and
+    // respawn instead of handing out the same defunct process forever.
+    alive: AtomicBool,
+}
 
-        let path_str = entry.path().to_string_lossy();
-        if let Some(g) = glob_pattern {
-            if !path_str.ends_with(g) { continue; }
+const LSP_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+impl LspServer {
+    async fn send_notification(&self, msg: serde_json::Value) -> std::io::Result<()> {
+        let mut stdin = self.stdin.lock().await;
+        write_lsp_message(&mut stdin, &msg).await
+    }
+
+    async fn send_request(&self, mut msg: serde_json::Value) -> std::io::Result<serde_json::Value> {
+        let obj = msg.as_object_mut().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "LSP request body must be a JSON object")
+        })?;
+        let id = match obj.get("id").cloned() {
+            Some(id) => id,
+            None => {
+                let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+                obj.insert("id".to_string(), serde_json::json!(id));
+                serde_json::json!(id)
+            }
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().await.insert(id.to_string(), tx);
+
+        {
+            let mut stdin = self.stdin.lock().await;
+            write_lsp_message(&mut stdin, &msg).await?;
         }
 
-        let rel_path = entry.path().strip_prefix(root).unwrap_or(entry.path())
-            .to_string_lossy().to_string();
-        let rel_path_lower = rel_path.to_ascii_lowercase();
+        match timeout(LSP_REQUEST_TIMEOUT, rx).await {
+            Ok(Ok(response)) => Ok(response),
+            Ok(Err(_)) => Err(std::io::Error::other("language server closed the connection")),
+            Err(_) => {
+                self.pending.lock().await.remove(&id.to_string());
+                Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "language server did not respond in time"))
+            }
+        }
+    }
 
-        // 1. Match Filename/Path
-        let mut path_matched = false;
-        if let Some(ref r) = re {
-            if r.is_match(&rel_path) { path_matched = true; }
+    /// Notifies the server of `uri`'s current contents: `textDocument/didOpen`
+    /// (version 1) the first time the URI is seen, `textDocument/didChange`
+    /// with a strictly increasing version on every call after that. Used by
+    /// both `get_file` reads and `save_file` writes, so a buffer opened once
+    /// never gets a second `didOpen` and every edit gets a version a real
+    /// server will actually accept.
+    async fn notify_document(&self, uri: &str, lang: &str, text: &str) -> std::io::Result<()> {
+        let mut documents = self.documents.lock().await;
+        if let Some(version) = documents.get_mut(uri) {
+            *version += 1;
+            let msg = serde_json::json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didChange",
+                "params": {
+                    "textDocument": { "uri": uri, "version": *version },
+                    "contentChanges": [{ "text": text }],
+                }
+            });
+            drop(documents);
+            self.send_notification(msg).await
         } else {
-            if rel_path_lower.contains(&query_lower) { path_matched = true; }
+            documents.insert(uri.to_string(), 1);
+            drop(documents);
+            self.send_notification(serde_json::json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didOpen",
+                "params": { "textDocument": { "uri": uri, "languageId": lang, "version": 1, "text": text } }
+            })).await
         }
+    }
+}
 
-        if path_matched {
-            results.push(SearchResult {
-                file: rel_path.clone(),
-                line: 1,
-                column: 1,
-                preview: format!("FILENAME MATCH: {}", rel_path),
-            });
+// --- Logic (Decoupled from Axum for testing) ---
+
+fn build_matcher(query: &str, use_regex: bool) -> Result<RegexMatcher, grep_regex::Error> {
+    let pattern = if use_regex { query.to_string() } else { regex::escape(query) };
+    RegexMatcherBuilder::new().case_insensitive(true).build(&pattern)
+}
+
+fn next_search_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let seq = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{:x}-{:x}", now(), seq)
+}
+
+/// Walks `root` with the `ignore` crate (so nested `.gitignore`/`.ignore`/global
+/// git excludes are honored automatically), streaming every hit to `tx` as it's
+/// found and bailing out as soon as `cancel` is tripped. Runs on a blocking
+/// thread (see `start_search`).
+#[allow(clippy::too_many_arguments)]
+fn run_search(
+    root: &Path,
+    matcher: RegexMatcher,
+    glob: Option<&str>,
+    respect_gitignore: bool,
+    include_hidden: bool,
+    extra_ignores: Arc<Vec<String>>,
+    max_archive_entry_bytes: u64,
+    cancel: CancellationToken,
+    tx: broadcast::Sender<SearchResult>,
+) {
+    let glob_pattern = glob.map(|g| g.trim_start_matches('*').to_string());
+
+    let walker = WalkBuilder::new(root)
+        .hidden(!include_hidden)
+        .git_ignore(respect_gitignore)
+        .git_global(respect_gitignore)
+        .git_exclude(respect_gitignore)
+        .ignore(respect_gitignore)
+        // .gitignore rules apply even when `root` isn't itself a git checkout
+        // (e.g. a plain directory opened in the editor).
+        .require_git(false)
+        .build_parallel();
+
+    walker.run(|| {
+        let root = root.to_path_buf();
+        let matcher = matcher.clone();
+        let glob_pattern = glob_pattern.clone();
+        let extra_ignores = extra_ignores.clone();
+        let cancel = cancel.clone();
+        let tx = tx.clone();
+
+        Box::new(move |entry| {
+            if cancel.is_cancelled() { return WalkState::Quit; }
+
+            let Ok(entry) = entry else { return WalkState::Continue };
+            if !entry.file_type().map(|t| t.is_file()).unwrap_or(false) { return WalkState::Continue; }
+
+            // Deployment-configured excludes, applied on top of .gitignore.
+            let components: Vec<_> = entry.path().components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect();
+            if components.iter().any(|c| extra_ignores.iter().any(|x| x == c)) {
+                return WalkState::Continue;
+            }
+
+            let rel_path = entry.path().strip_prefix(&root).unwrap_or(entry.path())
+                .to_string_lossy().to_string();
+
+            if let Some(ref g) = glob_pattern {
+                if !rel_path.ends_with(g.as_str()) { return WalkState::Continue; }
+            }
+
+            // 1. Match filename/path
+            if matcher.is_match(rel_path.as_bytes()).unwrap_or(false) {
+                let result = SearchResult {
+                    file: rel_path.clone(),
+                    line: 1,
+                    column: 1,
+                    preview: format!("FILENAME MATCH: {}", rel_path),
+                };
+                let _ = tx.send(result);
+            }
+
+            // 2. Match content, streaming each line hit as it's found
+            let Ok(content) = std::fs::read(entry.path()) else { return WalkState::Continue };
+
+            if let Some(kind) = archive_kind_for_path(entry.path()) {
+                search_archive_entries(kind, &content, &rel_path, &matcher, max_archive_entry_bytes, &tx, &cancel);
+                return WalkState::Continue;
+            }
+
+            if is_binary(&content) { return WalkState::Continue; }
+
+            search_bytes(&matcher, &rel_path, &content, &tx, &cancel);
+
+            WalkState::Continue
+        })
+    });
+}
+
+fn search_bytes(matcher: &RegexMatcher, file_label: &str, content: &[u8], tx: &broadcast::Sender<SearchResult>, cancel: &CancellationToken) {
+    let mut sink = ResultSink { rel_path: file_label, matcher, tx, cancel };
+    let _ = Searcher::new().search_slice(matcher, content, &mut sink);
+}
+
+/// Feeds each matched line from `grep-searcher` into the result channel,
+/// aborting the current file's search (by returning `Ok(false)`) once the
+/// caller cancels. A lack of current subscribers isn't a reason to stop:
+/// `broadcast::Sender::send` never blocks, and a client may still connect to
+/// `/api/search/stream` for this id after the walk has started.
+struct ResultSink<'a> {
+    rel_path: &'a str,
+    matcher: &'a RegexMatcher,
+    tx: &'a broadcast::Sender<SearchResult>,
+    cancel: &'a CancellationToken,
+}
+
+impl<'a> Sink for ResultSink<'a> {
+    type Error = std::io::Error;
+
+    fn matched(&mut self, _searcher: &Searcher, mat: &SinkMatch<'_>) -> Result<bool, Self::Error> {
+        if self.cancel.is_cancelled() { return Ok(false); }
+
+        let line = String::from_utf8_lossy(mat.bytes());
+        let column = self.matcher.find(mat.bytes()).ok().flatten().map(|m| m.start()).unwrap_or(0);
+        let result = SearchResult {
+            file: self.rel_path.to_string(),
+            line: mat.line_number().unwrap_or(0) as usize,
+            column: column + 1,
+            preview: line.trim().chars().take(200).collect(),
+        };
+        let _ = self.tx.send(result);
+        Ok(true)
+    }
+}
+
+/// Mirrors the rules `run_search`'s `ignore::WalkBuilder` applies (per-
+/// directory `.gitignore`/`.ignore`, the repo's `.git/info/exclude`, and the
+/// user's global git excludes) so the watcher and search never disagree on
+/// what's hidden. Per-directory matchers are cached, since rebuilding them
+/// from disk for every filesystem event would defeat the point of caching
+/// anything; an event touching a `.gitignore`/`.ignore` file itself evicts
+/// that directory's entry.
+struct IgnoreStack {
+    global: ignore::gitignore::Gitignore,
+    git_exclude: ignore::gitignore::Gitignore,
+    dirs: std::sync::Mutex<HashMap<PathBuf, Arc<ignore::gitignore::Gitignore>>>,
+}
+
+impl IgnoreStack {
+    fn new(root: &Path) -> IgnoreStack {
+        let (global, _) = ignore::gitignore::Gitignore::global();
+
+        let mut exclude_builder = ignore::gitignore::GitignoreBuilder::new(root);
+        let _ = exclude_builder.add(root.join(".git/info/exclude"));
+        let git_exclude = exclude_builder.build().unwrap_or_else(|_| ignore::gitignore::Gitignore::empty());
+
+        IgnoreStack { global, git_exclude, dirs: std::sync::Mutex::new(HashMap::new()) }
+    }
+
+    fn dir_matcher(&self, dir: &Path) -> Arc<ignore::gitignore::Gitignore> {
+        if let Some(gi) = self.dirs.lock().unwrap().get(dir) {
+            return gi.clone();
+        }
+        let mut builder = ignore::gitignore::GitignoreBuilder::new(dir);
+        let _ = builder.add(dir.join(".gitignore"));
+        let _ = builder.add(dir.join(".ignore"));
+        let gi = Arc::new(builder.build().unwrap_or_else(|_| ignore::gitignore::Gitignore::empty()));
+        self.dirs.lock().unwrap().insert(dir.to_path_buf(), gi.clone());
+        gi
+    }
+
+    /// Drops the cached matcher for `dir`, forcing the next lookup to
+    /// re-read its `.gitignore`/`.ignore` from disk.
+    fn invalidate(&self, dir: &Path) {
+        self.dirs.lock().unwrap().remove(dir);
+    }
+}
+
+/// Checks whether `path` should be hidden from watch results: either a
+/// deployment-configured extra-ignore component, or matched by the same
+/// `.gitignore`/`.ignore`/git-exclude/global-exclude rules `run_search`
+/// honors somewhere between `root` and the path's own directory.
+fn is_path_ignored(root: &Path, path: &Path, extra_ignores: &[String], ignores: &IgnoreStack) -> bool {
+    let components: Vec<_> = path.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect();
+    if components.iter().any(|c| extra_ignores.iter().any(|x| x == c)) {
+        return true;
+    }
+
+    let is_dir = path.is_dir();
+    if ignores.global.matched_path_or_any_parents(path, is_dir).is_ignore() { return true; }
+    if ignores.git_exclude.matched_path_or_any_parents(path, is_dir).is_ignore() { return true; }
+
+    let Ok(rel) = path.strip_prefix(root) else { return false };
+    let mut dir = root.to_path_buf();
+    if ignores.dir_matcher(&dir).matched_path_or_any_parents(path, is_dir).is_ignore() { return true; }
+    let ancestors: Vec<_> = rel.components().collect();
+    for component in ancestors.iter().take(ancestors.len().saturating_sub(1)) {
+        dir.push(component);
+        if ignores.dir_matcher(&dir).matched_path_or_any_parents(path, is_dir).is_ignore() { return true; }
+    }
+
+    false
+}
+
+/// Spawns a debounced recommended-watcher rooted at `root` and broadcasts a
+/// `ChangeEvent` for every create/modify/remove that survives ignore
+/// filtering. Runs for the lifetime of the process on its own thread, since
+/// `notify`'s blocking channel doesn't play well with the async runtime.
+fn spawn_watcher(root: PathBuf, extra_ignores: Arc<Vec<String>>, tx: broadcast::Sender<ChangeEvent>) {
+    std::thread::spawn(move || {
+        let (debounce_tx, debounce_rx) = std::sync::mpsc::channel();
+        let mut debouncer = match new_debouncer(Duration::from_millis(500), None, debounce_tx) {
+            Ok(d) => d,
+            Err(e) => { eprintln!("watcher: failed to initialize: {e}"); return; }
+        };
+        if let Err(e) = debouncer.watcher().watch(&root, RecursiveMode::Recursive) {
+            eprintln!("watcher: failed to watch {:?}: {e}", root);
+            return;
         }
 
-        // 2. Match Content
-        // Skip binary check for performance in test, but keep in prod
-        let Ok(content) = std::fs::read(entry.path()) else { continue };
-        if is_binary(&content) { continue; }
-        
-        let text = String::from_utf8_lossy(&content);
-
-        for (i, line) in text.lines().enumerate() {
-            let (found, col) = if let Some(ref r) = re {
-                if let Some(m) = r.find(line) { (true, m.start()) } else { (false, 0) }
-            } else {
-                match line.to_ascii_lowercase().find(&query_lower) {
-                    Some(idx) => (true, idx),
-                    None => (false, 0)
+        let ignores = IgnoreStack::new(&root);
+        for result in debounce_rx {
+            let events = match result {
+                Ok(events) => events,
+                Err(errors) => {
+                    for e in errors { eprintln!("watcher error: {e:?}"); }
+                    continue;
                 }
             };
+            for event in &events {
+                if let Some(change) = translate_event(&root, &extra_ignores, &ignores, event) {
+                    let _ = tx.send(change);
+                }
+            }
+        }
+    });
+}
 
-            if found {
-                results.push(SearchResult {
-                    file: rel_path.clone(),
-                    line: i + 1,
-                    column: col + 1,
-                    preview: line.trim().chars().take(200).collect(),
-                });
-                if results.len() > 2000 { break; }
+fn translate_event(root: &Path, extra_ignores: &[String], ignores: &IgnoreStack, event: &DebouncedEvent) -> Option<ChangeEvent> {
+    let path = event.paths.first()?;
+
+    if matches!(path.file_name().and_then(|n| n.to_str()), Some(".gitignore") | Some(".ignore")) {
+        if let Some(dir) = path.parent() { ignores.invalidate(dir); }
+    }
+
+    if is_path_ignored(root, path, extra_ignores, ignores) { return None; }
+
+    let kind = match event.kind {
+        notify::EventKind::Create(_) => "created",
+        notify::EventKind::Modify(_) => "modified",
+        notify::EventKind::Remove(_) => "removed",
+        _ => return None,
+    };
+
+    let rel_path = path.strip_prefix(root).unwrap_or(path).to_string_lossy().to_string();
+    // An atomic save (write-tmp-then-rename, the pattern `save_file` itself
+    // uses) is reported as a Create of the final path, not a Modify, so the
+    // etag has to be attached for both kinds or a real save would never
+    // carry one.
+    let etag = if kind == "created" || kind == "modified" {
+        std::fs::read(path).ok().map(|bytes| generate_etag(&bytes))
+    } else {
+        None
+    };
+
+    Some(ChangeEvent { kind: kind.to_string(), path: rel_path, etag })
+}
+
+async fn write_lsp_message(stdin: &mut ChildStdin, value: &serde_json::Value) -> std::io::Result<()> {
+    let body = serde_json::to_vec(value)?;
+    stdin.write_all(format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes()).await?;
+    stdin.write_all(&body).await?;
+    stdin.flush().await
+}
+
+async fn read_lsp_message<R: tokio::io::AsyncBufRead + Unpin>(reader: &mut R) -> std::io::Result<Option<serde_json::Value>> {
+    let mut content_length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 { return Ok(None); }
+        let trimmed = line.trim_end();
+        if trimmed.is_empty() { break; }
+        if let Some(rest) = trimmed.strip_prefix("Content-Length:") {
+            content_length = rest.trim().parse::<usize>().ok();
+        }
+    }
+
+    let len = content_length.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "missing Content-Length header"))?;
+    let mut buf = vec![0u8; len];
+    reader.read_exact(&mut buf).await?;
+    Ok(serde_json::from_slice(&buf).ok())
+}
+
+/// Derives the language id used to key `AppState::lsp_config`/`lsp_servers`
+/// from a file's extension, the same way an editor would pick a `languageId`.
+fn lang_for_path(rel_path: &str) -> Option<&'static str> {
+    match Path::new(rel_path).extension()?.to_str()? {
+        "rs" => Some("rust"),
+        "py" => Some("python"),
+        "ts" => Some("typescript"),
+        "tsx" => Some("typescriptreact"),
+        "js" | "mjs" | "cjs" => Some("javascript"),
+        "jsx" => Some("javascriptreact"),
+        "go" => Some("go"),
+        "c" | "h" => Some("c"),
+        "cpp" | "cc" | "cxx" | "hpp" => Some("cpp"),
+        _ => None,
+    }
+}
+
+fn load_lsp_config(config_dir: &Path) -> HashMap<String, LspServerConfig> {
+    let Ok(data) = std::fs::read(config_dir.join("lsp.json")) else { return HashMap::new() };
+    serde_json::from_slice(&data).unwrap_or_default()
+}
+
+fn file_uri(repo_root: &Path, rel_path: &str) -> String {
+    format!("file://{}", repo_root.join(rel_path).display())
+}
+
+/// Spawns the configured language server for `lang`, performs the
+/// `initialize`/`initialized` handshake with a single workspace folder rooted
+/// at `repo_root`, and starts a background task that demuxes responses (by
+/// `id`) from server-pushed notifications like `publishDiagnostics`.
+async fn spawn_lsp_server(lang: &str, cfg: &LspServerConfig, repo_root: &Path) -> std::io::Result<Arc<LspServer>> {
+    let mut child = Command::new(&cfg.command)
+        .args(&cfg.args)
+        .current_dir(repo_root)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .spawn()?;
+
+    let stdin = child.stdin.take().expect("child spawned with piped stdin");
+    let stdout = child.stdout.take().expect("child spawned with piped stdout");
+
+    let pending: Arc<AsyncMutex<HashMap<String, oneshot::Sender<serde_json::Value>>>> = Arc::new(AsyncMutex::new(HashMap::new()));
+    let (diagnostics_tx, _) = broadcast::channel(256);
+
+    let server = Arc::new(LspServer {
+        stdin: AsyncMutex::new(stdin),
+        pending: pending.clone(),
+        diagnostics_tx: diagnostics_tx.clone(),
+        next_id: AtomicU64::new(1),
+        documents: AsyncMutex::new(HashMap::new()),
+        alive: AtomicBool::new(true),
+    });
+    let reader_server = server.clone();
+
+    tokio::spawn(async move {
+        let mut reader = BufReader::new(stdout);
+        while let Ok(Some(msg)) = read_lsp_message(&mut reader).await {
+            if let Some(id) = msg.get("id") {
+                if let Some(tx) = pending.lock().await.remove(&id.to_string()) {
+                    let _ = tx.send(msg);
+                    continue;
+                }
+            }
+            if msg.get("method").and_then(|m| m.as_str()) == Some("textDocument/publishDiagnostics") {
+                let _ = diagnostics_tx.send(msg);
             }
         }
-        if results.len() > 2000 { break; }
+        // The child's stdout closed - mark it dead and drop every outstanding
+        // request's sender, so send_request's rx.await resolves immediately
+        // instead of hanging (or waiting out its full timeout) for a reply
+        // that can now never come.
+        reader_server.alive.store(false, Ordering::Relaxed);
+        pending.lock().await.clear();
+        let _ = child.wait().await;
+    });
+
+    let root_uri = format!("file://{}", repo_root.display());
+    let init_request = serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": "initialize",
+        "params": {
+            "processId": std::process::id(),
+            "rootUri": root_uri,
+            "workspaceFolders": [{ "uri": root_uri, "name": lang }],
+            "capabilities": {},
+        }
+    });
+    server.send_request(init_request).await?;
+    server.send_notification(serde_json::json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} })).await?;
+
+    Ok(server)
+}
+
+/// Gets (or creates) the still-empty `OnceCell` that will hold `lang`'s
+/// server. The map lock is only held long enough for this get-or-insert; the
+/// (potentially multi-second) spawn/initialize handshake runs on the cell
+/// itself, so a slow server for one language never blocks lookups for any
+/// other.
+async fn lsp_cell(state: &AppState, lang: &str) -> LspServerCell {
+    let mut servers = state.lsp_servers.lock().await;
+    servers.entry(lang.to_string()).or_insert_with(|| Arc::new(OnceCell::new())).clone()
+}
+
+/// Returns the running language server for `lang`, spawning it on first use.
+/// If the cached server's process has since died, its (permanently resolved)
+/// cell is evicted and a fresh one spawned, so a crashed language server
+/// doesn't get handed out forever.
+async fn get_or_spawn_lsp(state: &AppState, lang: &str) -> std::io::Result<Arc<LspServer>> {
+    let cfg = state.lsp_config.get(lang).ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::NotFound, format!("no LSP server configured for language '{lang}'"))
+    })?;
+
+    let cell = lsp_cell(state, lang).await;
+    let server = cell.get_or_try_init(|| spawn_lsp_server(lang, cfg, &state.repo_root)).await?.clone();
+    if server.alive.load(Ordering::Relaxed) {
+        return Ok(server);
+    }
+
+    let mut servers = state.lsp_servers.lock().await;
+    if matches!(servers.get(lang), Some(existing) if Arc::ptr_eq(existing, &cell)) {
+        servers.remove(lang);
     }
+    drop(servers);
 
-    results
+    let cell = lsp_cell(state, lang).await;
+    let server = cell.get_or_try_init(|| spawn_lsp_server(lang, cfg, &state.repo_root)).await?;
+    Ok(server.clone())
 }
 
 fn now() -> u64 {
@@ -163,41 +683,389 @@ fn generate_etag(bytes: &[u8]) -> String {
     blake3::hash(bytes).to_hex().to_string()
 }
 
+/// Resolves `rel` against `root` and verifies the *real*, symlink-resolved
+/// path stays inside `root` before returning it. `save_file` writes to paths
+/// that may not exist yet, so when the joined path itself can't be
+/// canonicalized, its parent directory is canonicalized instead and the
+/// final component is reattached.
 fn safe_path(root: &Path, rel: &str) -> anyhow::Result<PathBuf> {
-    if rel.contains("..") { return Err(anyhow::anyhow!("Invalid path")); }
-    Ok(root.join(rel))
+    if Path::new(rel).is_absolute() {
+        return Err(anyhow::anyhow!("Invalid path"));
+    }
+    let joined = root.join(rel);
+
+    let (real_base, file_name) = match joined.canonicalize() {
+        Ok(real) => (real, None),
+        Err(_) => {
+            let parent = joined.parent().ok_or_else(|| anyhow::anyhow!("Invalid path"))?;
+            let file_name = joined.file_name().ok_or_else(|| anyhow::anyhow!("Invalid path"))?;
+            let real_parent = parent.canonicalize().map_err(|_| anyhow::anyhow!("Invalid path"))?;
+            (real_parent, Some(file_name.to_owned()))
+        }
+    };
+
+    let real_root = root.canonicalize()?;
+    if !real_base.starts_with(&real_root) {
+        return Err(anyhow::anyhow!("Path escapes repository root"));
+    }
+
+    Ok(match file_name {
+        Some(name) => real_base.join(name),
+        None => real_base,
+    })
 }
 
 fn is_binary(data: &[u8]) -> bool {
     data.iter().take(8192).any(|&b| b == 0)
 }
 
+const DEFAULT_MAX_ARCHIVE_ENTRY_BYTES: u64 = 50 * 1024 * 1024;
+
+#[derive(Clone, Copy)]
+enum ArchiveKind {
+    Zip,
+    TarGz,
+}
+
+fn archive_kind_for_path(path: &Path) -> Option<ArchiveKind> {
+    let name = path.to_string_lossy();
+    if name.ends_with(".zip") {
+        Some(ArchiveKind::Zip)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(ArchiveKind::TarGz)
+    } else {
+        None
+    }
+}
+
+/// Splits a `fixtures/data.tar.gz!entry/inner.txt`-style path into its
+/// archive and in-archive parts, only when the first segment is a supported
+/// archive type.
+fn split_archive_path(path: &str) -> Option<(&str, &str)> {
+    let (archive, entry) = path.split_once('!')?;
+    archive_kind_for_path(Path::new(archive)).map(|_| (archive, entry))
+}
+
+/// Reads a single archive entry's decompressed bytes, bounded by
+/// `max_entry_bytes`. The bound is enforced against the bytes actually read
+/// out of the decompressor rather than either archive format's declared
+/// uncompressed-size metadata, since that's attacker-controlled and zip in
+/// particular only bounds reads by *compressed* size - a crafted entry can
+/// under-report its size and still inflate past it.
+fn read_archive_entry(root: &Path, archive_rel: &str, entry_path: &str, max_entry_bytes: u64) -> anyhow::Result<Vec<u8>> {
+    let archive_path = safe_path(root, archive_rel)?;
+    let kind = archive_kind_for_path(&archive_path).ok_or_else(|| anyhow::anyhow!("unsupported archive type"))?;
+    let mut buf = Vec::new();
+
+    match kind {
+        ArchiveKind::Zip => {
+            let file = std::fs::File::open(&archive_path)?;
+            let mut zip = zip::ZipArchive::new(file)?;
+            let mut entry = zip.by_name(entry_path)?;
+            let mut limited = std::io::Read::take(&mut entry, max_entry_bytes + 1);
+            std::io::Read::read_to_end(&mut limited, &mut buf)?;
+        }
+        ArchiveKind::TarGz => {
+            let file = std::fs::File::open(&archive_path)?;
+            let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+            let mut found = false;
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                if entry.path()?.to_string_lossy() == entry_path {
+                    let mut limited = std::io::Read::take(&mut entry, max_entry_bytes + 1);
+                    std::io::Read::read_to_end(&mut limited, &mut buf)?;
+                    found = true;
+                    break;
+                }
+            }
+            if !found {
+                return Err(anyhow::anyhow!("entry '{}' not found in archive", entry_path));
+            }
+        }
+    }
+
+    if buf.len() as u64 > max_entry_bytes {
+        return Err(anyhow::anyhow!("entry '{}' exceeds the {}-byte archive entry limit", entry_path, max_entry_bytes));
+    }
+
+    Ok(buf)
+}
+
+/// Descends into a zip/tar.gz archive's entries (each bounded by
+/// `max_entry_bytes` of actual decompressed output, to avoid a zip bomb -
+/// the archive's declared uncompressed size is attacker-controlled and not
+/// trusted) and runs the same line-by-line search used for ordinary files,
+/// reporting matches with the `archive!entry` path form.
+fn search_archive_entries(
+    kind: ArchiveKind,
+    archive_bytes: &[u8],
+    archive_rel_path: &str,
+    matcher: &RegexMatcher,
+    max_entry_bytes: u64,
+    tx: &broadcast::Sender<SearchResult>,
+    cancel: &CancellationToken,
+) {
+    match kind {
+        ArchiveKind::Zip => {
+            let Ok(mut zip) = zip::ZipArchive::new(std::io::Cursor::new(archive_bytes)) else { return };
+            for i in 0..zip.len() {
+                if cancel.is_cancelled() { return; }
+                let Ok(mut file) = zip.by_index(i) else { continue };
+                if file.is_dir() { continue; }
+                let label = format!("{}!{}", archive_rel_path, file.name());
+                let mut buf = Vec::new();
+                // Bound by bytes actually decompressed, not the entry's declared
+                // size - that's attacker-controlled and zip only enforces reads
+                // by compressed size, so a crafted entry can under-report it.
+                let mut limited = std::io::Read::take(&mut file, max_entry_bytes + 1);
+                if std::io::Read::read_to_end(&mut limited, &mut buf).is_err() { continue; }
+                if buf.len() as u64 > max_entry_bytes { continue; }
+                if is_binary(&buf) { continue; }
+                search_bytes(matcher, &label, &buf, tx, cancel);
+            }
+        }
+        ArchiveKind::TarGz => {
+            let decoder = flate2::read::GzDecoder::new(std::io::Cursor::new(archive_bytes));
+            let mut archive = tar::Archive::new(decoder);
+            let Ok(entries) = archive.entries() else { return };
+            for entry in entries {
+                if cancel.is_cancelled() { return; }
+                let Ok(mut entry) = entry else { continue };
+                if !entry.header().entry_type().is_file() { continue; }
+                let Ok(entry_name) = entry.path().map(|p| p.to_string_lossy().to_string()) else { continue };
+                let label = format!("{}!{}", archive_rel_path, entry_name);
+                let mut buf = Vec::new();
+                let mut limited = std::io::Read::take(&mut entry, max_entry_bytes + 1);
+                if std::io::Read::read_to_end(&mut limited, &mut buf).is_err() { continue; }
+                if buf.len() as u64 > max_entry_bytes { continue; }
+                if is_binary(&buf) { continue; }
+                search_bytes(matcher, &label, &buf, tx, cancel);
+            }
+        }
+    }
+}
+
+/// The fixed set of defensive response headers applied to every response,
+/// the way bitwarden_rs's `AppHeaders` middleware does: nosniff,
+/// frame-busting, a restrictive CSP, and a same-origin referrer policy.
+/// Pulled out of `security_headers` so the actual header values are
+/// testable without standing up the axum middleware stack.
+fn security_header_values() -> [(header::HeaderName, HeaderValue); 4] {
+    [
+        (header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff")),
+        (header::X_FRAME_OPTIONS, HeaderValue::from_static("SAMEORIGIN")),
+        (
+            header::CONTENT_SECURITY_POLICY,
+            HeaderValue::from_static("default-src 'self'; script-src 'self'; style-src 'self' 'unsafe-inline'; img-src 'self' data:; connect-src 'self'"),
+        ),
+        (header::REFERRER_POLICY, HeaderValue::from_static("same-origin")),
+    ]
+}
+
+async fn security_headers(request: Request, next: Next) -> Response {
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    for (name, value) in security_header_values() {
+        headers.insert(name, value);
+    }
+    response
+}
+
+/// Parses a configurable origin allow-list into the `HeaderValue`s
+/// `build_cors_layer` hands to `AllowOrigin::list`. Entries that don't parse
+/// as a valid header value (not a syntax-checked origin - axum/tower-http
+/// don't validate further) are silently dropped rather than granted access.
+fn parse_allowed_origins(allowed_origins: &[String]) -> Vec<HeaderValue> {
+    allowed_origins.iter().filter_map(|o| o.parse().ok()).collect()
+}
+
+/// Builds a CORS layer from a configurable origin allow-list. With no origins
+/// configured, cross-origin requests simply aren't granted access (browsers
+/// still allow same-origin requests, which is all the bundled web UI needs).
+fn build_cors_layer(allowed_origins: &[String]) -> CorsLayer {
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(parse_allowed_origins(allowed_origins)))
+        .allow_methods(tower_http::cors::Any)
+        .allow_headers(tower_http::cors::Any)
+}
+
 // --- Handlers ---
 
-async fn search(
+async fn start_search(
     State(state): State<AppState>,
-    Query(params): Query<SearchParams>,
-) -> Json<Vec<SearchResult>> {
-    let results = perform_search(
-        &state.repo_root, 
-        &params.q, 
-        params.regex.unwrap_or(false), 
-        params.glob.as_deref()
+    Query(params): Query<SearchStartParams>,
+) -> Result<Json<SearchStartResponse>, (StatusCode, String)> {
+    let matcher = build_matcher(&params.q, params.regex.unwrap_or(false))
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let search_id = next_search_id();
+    let cancel = CancellationToken::new();
+    let (tx, _) = broadcast::channel(256);
+
+    let root = state.repo_root.clone();
+    let glob = params.glob.clone();
+    let respect_gitignore = params.respect_gitignore.unwrap_or(true);
+    let include_hidden = params.include_hidden.unwrap_or(false);
+    let extra_ignores = state.extra_ignores.clone();
+    let max_archive_entry_bytes = state.max_archive_entry_bytes;
+    let search_tx = tx.clone();
+    let search_cancel = cancel.clone();
+    let cleanup_state = state.clone();
+    let cleanup_id = search_id.clone();
+
+    // Not spawned yet - `stream_search` runs this once a subscriber exists,
+    // so nothing is ever published before a receiver is there to see it.
+    let start: StartSearch = Box::new(move || {
+        let handle = tokio::task::spawn_blocking(move || {
+            run_search(&root, matcher, glob.as_deref(), respect_gitignore, include_hidden, extra_ignores, max_archive_entry_bytes, search_cancel, search_tx)
+        });
+        // Once the walk finishes - normally or via cancellation - drop this
+        // search from both maps, so a long-lived server doesn't accumulate one
+        // cancellation token and broadcast channel per search ever run.
+        tokio::spawn(async move {
+            let _ = handle.await;
+            cleanup_state.searches.write().unwrap().remove(&cleanup_id);
+            cleanup_state.search_streams.write().unwrap().remove(&cleanup_id);
+        });
+    });
+
+    state.searches.write().unwrap().insert(search_id.clone(), cancel);
+    state.search_streams.write().unwrap().insert(
+        search_id.clone(),
+        Arc::new(SearchEntry { tx, start: std::sync::Mutex::new(Some(start)) }),
     );
-    Json(results)
+
+    Ok(Json(SearchStartResponse { search_id }))
+}
+
+async fn stream_search(
+    State(state): State<AppState>,
+    Query(params): Query<SearchIdParams>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, StatusCode> {
+    let entry = state.search_streams.read().unwrap()
+        .get(&params.id)
+        .cloned()
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    // Subscribe before starting the walk (a no-op on reconnect, since `start`
+    // is only `Some` once): a receiver must exist before anything can be
+    // published, or results found in between would be silently dropped.
+    let rx = entry.tx.subscribe();
+    if let Some(start) = entry.start.lock().unwrap().take() {
+        start();
+    }
+
+    let stream = BroadcastStream::new(rx).filter_map(|msg| async move {
+        match msg {
+            Ok(result) => Some(Ok(Event::default().json_data(&result).unwrap_or_else(|_| Event::default()))),
+            // A lagging receiver dropped some results; keep streaming rather than killing the connection.
+            Err(_) => None,
+        }
+    });
+    Ok(Sse::new(stream))
+}
+
+async fn cancel_search(
+    State(state): State<AppState>,
+    Query(params): Query<SearchIdParams>,
+) -> StatusCode {
+    let token = state.searches.write().unwrap().remove(&params.id);
+    match token {
+        Some(token) => {
+            token.cancel();
+            // A search cancelled before anyone ever streamed it would
+            // otherwise never start, and so never hit the cleanup that runs
+            // once a started walk finishes - remove it here too.
+            state.search_streams.write().unwrap().remove(&params.id);
+            StatusCode::OK
+        }
+        None => StatusCode::NOT_FOUND,
+    }
+}
+
+async fn watch_events(
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let rx = state.change_tx.subscribe();
+    let stream = BroadcastStream::new(rx).filter_map(|msg| async move {
+        match msg {
+            Ok(change) => Some(Ok(Event::default().json_data(&change).unwrap_or_else(|_| Event::default()))),
+            // A lagging receiver dropped some events; keep streaming rather than killing the connection.
+            Err(_) => None,
+        }
+    });
+    Sse::new(stream)
+}
+
+async fn lsp_forward(
+    State(state): State<AppState>,
+    RoutePath(lang): RoutePath<String>,
+    Json(body): Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if !body.is_object() {
+        return Err((StatusCode::BAD_REQUEST, "LSP request body must be a JSON object".to_string()));
+    }
+    let server = get_or_spawn_lsp(&state, &lang).await.map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+    let response = server.send_request(body).await.map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+    Ok(Json(response))
+}
+
+async fn lsp_notifications(
+    State(state): State<AppState>,
+    RoutePath(lang): RoutePath<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, StatusCode> {
+    let server = get_or_spawn_lsp(&state, &lang).await.map_err(|_| StatusCode::NOT_FOUND)?;
+    let rx = server.diagnostics_tx.subscribe();
+    let stream = BroadcastStream::new(rx).filter_map(|msg| async move {
+        match msg {
+            Ok(diagnostics) => Some(Ok(Event::default().json_data(&diagnostics).unwrap_or_else(|_| Event::default()))),
+            Err(_) => None,
+        }
+    });
+    Ok(Sse::new(stream))
+}
+
+/// Best-effort `textDocument/didOpen`/`didChange` so diagnostics track the
+/// same buffer `get_file`/`save_file` just served, sent via
+/// `LspServer::notify_document` so a buffer already open gets a `didChange`
+/// with an increasing version instead of a second, spec-violating `didOpen`.
+/// Spawned fire-and-forget: a missing/slow language server should never hold
+/// up the file read or write.
+fn notify_lsp_document(state: &AppState, rel_path: &str, text: &str) {
+    let Some(lang) = lang_for_path(rel_path) else { return };
+    let state = state.clone();
+    let uri = file_uri(&state.repo_root, rel_path);
+    let text = text.to_string();
+    tokio::spawn(async move {
+        if let Ok(server) = get_or_spawn_lsp(&state, lang).await {
+            let _ = server.notify_document(&uri, lang, &text).await;
+        }
+    });
 }
 
 async fn get_file(
     State(state): State<AppState>,
     Query(params): Query<FileParams>,
 ) -> Result<Json<FileResponse>, StatusCode> {
-    let path = safe_path(&state.repo_root, &params.path).map_err(|_| StatusCode::BAD_REQUEST)?;
+    if let Some((archive_rel, entry_path)) = split_archive_path(&params.path) {
+        let bytes = read_archive_entry(&state.repo_root, archive_rel, entry_path, state.max_archive_entry_bytes)
+            .map_err(|_| StatusCode::NOT_FOUND)?;
+        if is_binary(&bytes) { return Err(StatusCode::UNSUPPORTED_MEDIA_TYPE); }
+        return Ok(Json(FileResponse {
+            etag: generate_etag(&bytes),
+            content: String::from_utf8_lossy(&bytes).to_string(),
+        }));
+    }
+
+    let path = safe_path(&state.repo_root, &params.path).map_err(|_| StatusCode::FORBIDDEN)?;
     match std::fs::read(&path) {
         Ok(bytes) => {
             if is_binary(&bytes) { return Err(StatusCode::UNSUPPORTED_MEDIA_TYPE); }
+            let content = String::from_utf8_lossy(&bytes).to_string();
+            notify_lsp_document(&state, &params.path, &content);
             Ok(Json(FileResponse {
                 etag: generate_etag(&bytes),
-                content: String::from_utf8_lossy(&bytes).to_string(),
+                content,
             }))
         },
         Err(_) => Err(StatusCode::NOT_FOUND),
@@ -208,7 +1076,11 @@ async fn save_file(
     State(state): State<AppState>,
     Json(req): Json<SaveRequest>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    let path = safe_path(&state.repo_root, &req.path).map_err(|_| (StatusCode::BAD_REQUEST, "Invalid path".into()))?;
+    if split_archive_path(&req.path).is_some() {
+        return Err((StatusCode::UNSUPPORTED_MEDIA_TYPE, "Saving into archives is not supported".into()));
+    }
+
+    let path = safe_path(&state.repo_root, &req.path).map_err(|_| (StatusCode::FORBIDDEN, "Invalid path".into()))?;
 
     if path.exists() {
         let current_bytes = std::fs::read(&path).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -229,6 +1101,7 @@ async fn save_file(
         return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
     }
     let new_etag = generate_etag(req.content.as_bytes());
+    notify_lsp_document(&state, &req.path, &req.content);
     Ok(Json(serde_json::json!({ "status": "ok", "new_etag": new_etag })))
 }
 
@@ -261,10 +1134,10 @@ async fn patch_checklist(
 async fn main() {
     tracing_subscriber::fmt::init();
     let args: Vec<String> = std::env::args().collect();
-    
+
     // Use arg if provided, otherwise current dir
     let raw_path = if args.len() > 1 { PathBuf::from(&args[1]) } else { std::env::current_dir().unwrap() };
-    
+
     let repo_root = std::fs::canonicalize(&raw_path).unwrap_or_else(|_| {
         eprintln!("Error: Directory '{:?}' not found.", raw_path);
         std::process::exit(1);
@@ -278,16 +1151,46 @@ async fn main() {
         serde_json::from_slice(&data).unwrap_or_default()
     } else { BTreeMap::new() };
 
+    let extra_ignores: Vec<String> = std::env::var("CODEEDIT_EXTRA_IGNORES")
+        .map(|raw| raw.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+
+    let extra_ignores = Arc::new(extra_ignores);
+    let (change_tx, _) = broadcast::channel(256);
+    spawn_watcher(repo_root.clone(), extra_ignores.clone(), change_tx.clone());
+
+    let lsp_config = Arc::new(load_lsp_config(&repo_root.join("codeedit")));
+
+    let allowed_origins: Vec<String> = std::env::var("CODEEDIT_ALLOWED_ORIGINS")
+        .map(|raw| raw.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+
     let state = AppState {
         repo_root, checklist_path, checklist: Arc::new(RwLock::new(checklist_map)),
+        searches: Arc::new(RwLock::new(HashMap::new())),
+        search_streams: Arc::new(RwLock::new(HashMap::new())),
+        extra_ignores,
+        change_tx,
+        lsp_config,
+        lsp_servers: Arc::new(AsyncMutex::new(HashMap::new())),
+        max_archive_entry_bytes: std::env::var("CODEEDIT_MAX_ARCHIVE_ENTRY_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_ARCHIVE_ENTRY_BYTES),
     };
 
     let app = Router::new()
-        .route("/api/search", get(search))
+        .route("/api/search/start", post(start_search))
+        .route("/api/search/stream", get(stream_search))
+        .route("/api/search/cancel", post(cancel_search))
+        .route("/api/events", get(watch_events))
+        .route("/api/lsp/:lang", post(lsp_forward))
+        .route("/api/lsp/:lang/notifications", get(lsp_notifications))
         .route("/api/file", get(get_file).post(save_file))
         .route("/api/checklist", get(get_checklist).patch(patch_checklist))
-        .nest_service("/", ServeDir::new("../web/dist")) 
-        .layer(CorsLayer::permissive()) 
+        .nest_service("/", ServeDir::new("../web/dist"))
+        .layer(middleware::from_fn(security_headers))
+        .layer(build_cors_layer(&allowed_origins))
         .with_state(state);
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
@@ -309,19 +1212,23 @@ mod tests {
     fn setup_env() -> (TempDir, PathBuf) {
         let temp_dir = TempDir::new().unwrap();
         let root = temp_dir.path().to_path_buf();
-        
+
         // Create structure
         // root/
+        //   .gitignore (ignores target/)
         //   src/
         //     main.rs (contains "fn main")
         //     util.rs (contains "pub fn help")
         //   README.md (contains "TODO list")
         //   target/
         //     ignore_me.rs (contains "fn main")
-        
+
         fs::create_dir(root.join("src")).unwrap();
         fs::create_dir(root.join("target")).unwrap();
 
+        let mut f = fs::File::create(root.join(".gitignore")).unwrap();
+        writeln!(f, "target/").unwrap();
+
         let mut f = fs::File::create(root.join("src/main.rs")).unwrap();
         writeln!(f, "fn main() {{ println!(\"Hello\"); }}").unwrap();
 
@@ -337,34 +1244,265 @@ mod tests {
         (temp_dir, root)
     }
 
-    #[test]
-    fn test_search_content_substring() {
+    // Drives `run_search` to completion on a blocking thread and collects
+    // every streamed result, mirroring what the SSE endpoint would emit.
+    async fn collect_search(root: &Path, query: &str, use_regex: bool, glob: Option<&str>) -> Vec<SearchResult> {
+        collect_search_with(root, query, use_regex, glob, true, false, &[]).await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn collect_search_with(
+        root: &Path,
+        query: &str,
+        use_regex: bool,
+        glob: Option<&str>,
+        respect_gitignore: bool,
+        include_hidden: bool,
+        extra_ignores: &[&str],
+    ) -> Vec<SearchResult> {
+        let matcher = build_matcher(query, use_regex).unwrap();
+        let (tx, mut rx) = broadcast::channel(256);
+        let cancel = CancellationToken::new();
+        let root = root.to_path_buf();
+        let glob = glob.map(|g| g.to_string());
+        let extra_ignores = Arc::new(extra_ignores.iter().map(|s| s.to_string()).collect());
+        tokio::task::spawn_blocking(move || {
+            run_search(&root, matcher, glob.as_deref(), respect_gitignore, include_hidden, extra_ignores, DEFAULT_MAX_ARCHIVE_ENTRY_BYTES, cancel, tx)
+        });
+
+        let mut results = Vec::new();
+        loop {
+            match rx.recv().await {
+                Ok(r) => results.push(r),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+        results
+    }
+
+    #[tokio::test]
+    async fn test_search_content_substring() {
         let (_tmp, root) = setup_env();
-        let results = perform_search(&root, "println", false, None);
-        
+        let results = collect_search(&root, "println", false, None).await;
+
         assert_eq!(results.len(), 1);
         assert!(results[0].file.contains("main.rs"));
         assert!(results[0].preview.contains("fn main"));
     }
 
-    #[test]
-    fn test_search_filename() {
+    #[tokio::test]
+    async fn test_search_filename() {
         let (_tmp, root) = setup_env();
-        let results = perform_search(&root, "util.rs", false, None);
-        
+        let results = collect_search(&root, "util.rs", false, None).await;
+
         assert_eq!(results.len(), 1);
         assert!(results[0].file.contains("util.rs"));
         assert!(results[0].preview.contains("FILENAME MATCH"));
     }
 
-    #[test]
-    fn test_search_ignore_target() {
+    #[tokio::test]
+    async fn test_search_respects_gitignore() {
         let (_tmp, root) = setup_env();
-        // "fn main" appears in src/main.rs AND target/ignore_me.rs
-        // But perform_search should skip 'target'
-        let results = perform_search(&root, "fn main", false, None);
-        
+        // "fn main" appears in src/main.rs AND target/ignore_me.rs, but the
+        // repo's own .gitignore excludes target/.
+        let results = collect_search(&root, "fn main", false, None).await;
+
         assert_eq!(results.len(), 1);
         assert!(results[0].file.contains("src")); // Ensure we got the src one, not target
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_search_gitignore_can_be_disabled() {
+        let (_tmp, root) = setup_env();
+        let results = collect_search_with(&root, "fn main", false, None, false, false, &[]).await;
+
+        // With respect_gitignore=false, target/ignore_me.rs is walked too.
+        assert_eq!(results.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_search_extra_ignores() {
+        let (_tmp, root) = setup_env();
+        // Disable .gitignore handling but fall back to a deployment-configured
+        // exclude list, which should still keep target/ out of the results.
+        let results = collect_search_with(&root, "fn main", false, None, false, false, &["target"]).await;
+
+        assert_eq!(results.len(), 1);
+        assert!(results[0].file.contains("src"));
+    }
+
+    fn write_zip_fixture(path: &Path) {
+        let file = fs::File::create(path).unwrap();
+        let mut zip = zip::ZipWriter::new(file);
+        let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        zip.start_file("inner.txt", options).unwrap();
+        zip.write_all(b"needle inside the archive\n").unwrap();
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn test_split_archive_path() {
+        assert_eq!(split_archive_path("fixtures/data.zip!inner.txt"), Some(("fixtures/data.zip", "inner.txt")));
+        assert_eq!(split_archive_path("fixtures/data.tar.gz!dir/inner.txt"), Some(("fixtures/data.tar.gz", "dir/inner.txt")));
+        assert_eq!(split_archive_path("src/main.rs"), None);
+    }
+
+    #[test]
+    fn test_read_archive_entry_zip() {
+        let (_tmp, root) = setup_env();
+        write_zip_fixture(&root.join("fixture.zip"));
+
+        let bytes = read_archive_entry(&root, "fixture.zip", "inner.txt", DEFAULT_MAX_ARCHIVE_ENTRY_BYTES).unwrap();
+        assert_eq!(String::from_utf8(bytes).unwrap(), "needle inside the archive\n");
+
+        assert!(read_archive_entry(&root, "fixture.zip", "missing.txt", DEFAULT_MAX_ARCHIVE_ENTRY_BYTES).is_err());
+    }
+
+    #[test]
+    fn test_read_archive_entry_enforces_byte_limit() {
+        let (_tmp, root) = setup_env();
+        write_zip_fixture(&root.join("fixture.zip"));
+
+        // "needle inside the archive\n" is 26 bytes; a limit below that must reject it,
+        // checked against the bytes actually read out of the entry.
+        assert!(read_archive_entry(&root, "fixture.zip", "inner.txt", 10).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_search_descends_into_zip_archive() {
+        let (_tmp, root) = setup_env();
+        write_zip_fixture(&root.join("fixture.zip"));
+
+        let results = collect_search(&root, "needle", false, None).await;
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].file, "fixture.zip!inner.txt");
+    }
+
+    #[test]
+    fn test_safe_path_rejects_escape() {
+        let (_tmp, root) = setup_env();
+        assert!(safe_path(&root, "../outside.txt").is_err());
+        assert!(safe_path(&root, "/etc/passwd").is_err());
+    }
+
+    #[test]
+    fn test_safe_path_allows_new_file_in_existing_dir() {
+        let (_tmp, root) = setup_env();
+        let resolved = safe_path(&root, "src/new_file.rs").unwrap();
+        assert!(resolved.starts_with(root.canonicalize().unwrap()));
+        assert_eq!(resolved.file_name().unwrap(), "new_file.rs");
+    }
+
+    #[test]
+    fn test_safe_path_rejects_symlink_escape() {
+        let (_tmp, root) = setup_env();
+        let outside = TempDir::new().unwrap();
+        fs::write(outside.path().join("secret.txt"), b"shh").unwrap();
+
+        // A symlink inside root pointing at a directory outside it - the
+        // naive ".." / absolute-path check alone would wave this straight
+        // through, since the path text itself never leaves root.
+        std::os::unix::fs::symlink(outside.path(), root.join("escape_link")).unwrap();
+
+        assert!(safe_path(&root, "escape_link/secret.txt").is_err());
+    }
+
+    #[test]
+    fn test_lang_for_path() {
+        assert_eq!(lang_for_path("src/main.rs"), Some("rust"));
+        assert_eq!(lang_for_path("scripts/build.py"), Some("python"));
+        assert_eq!(lang_for_path("README.md"), None);
+    }
+
+    #[test]
+    fn test_load_lsp_config_missing_file_is_empty() {
+        let (_tmp, root) = setup_env();
+        assert!(load_lsp_config(&root.join("codeedit")).is_empty());
+    }
+
+    #[test]
+    fn test_is_path_ignored_respects_gitignore_and_extras() {
+        let (_tmp, root) = setup_env();
+        let ignores = IgnoreStack::new(&root);
+
+        assert!(is_path_ignored(&root, &root.join("target/ignore_me.rs"), &[], &ignores));
+        assert!(!is_path_ignored(&root, &root.join("src/main.rs"), &[], &ignores));
+        assert!(is_path_ignored(&root, &root.join("README.md"), &["README.md".to_string()], &ignores));
+    }
+
+    #[test]
+    fn test_is_path_ignored_honors_dot_ignore_and_invalidates_cache() {
+        let (_tmp, root) = setup_env();
+        let ignores = IgnoreStack::new(&root);
+
+        // Same rules run_search's WalkBuilder applies also cover .ignore
+        // files, not just .gitignore.
+        assert!(!is_path_ignored(&root, &root.join("README.md"), &[], &ignores));
+
+        let mut f = fs::File::create(root.join(".ignore")).unwrap();
+        writeln!(f, "README.md").unwrap();
+
+        // Stale cached entry still says "not ignored" until invalidated.
+        assert!(!is_path_ignored(&root, &root.join("README.md"), &[], &ignores));
+        ignores.invalidate(&root);
+        assert!(is_path_ignored(&root, &root.join("README.md"), &[], &ignores));
+    }
+
+    #[test]
+    fn test_translate_event_attaches_etag_for_create() {
+        let (_tmp, root) = setup_env();
+        let path = root.join("src/main.rs");
+        let bytes = fs::read(&path).unwrap();
+
+        // Atomic saves are reported as a Create of the final path, not a
+        // Modify - verify the etag still gets attached in that case.
+        let event = notify::Event::new(notify::EventKind::Create(notify::event::CreateKind::Any))
+            .add_path(path.clone());
+        let debounced = DebouncedEvent::from(event);
+        let ignores = IgnoreStack::new(&root);
+
+        let change = translate_event(&root, &[], &ignores, &debounced).unwrap();
+        assert_eq!(change.kind, "created");
+        assert_eq!(change.etag, Some(generate_etag(&bytes)));
+    }
+
+    #[tokio::test]
+    async fn test_search_cancel_stops_early() {
+        let (_tmp, root) = setup_env();
+        let matcher = build_matcher("fn", false).unwrap();
+        let (tx, mut rx) = broadcast::channel(256);
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+
+        let root_clone = root.clone();
+        let extra_ignores = Arc::new(Vec::new());
+        tokio::task::spawn_blocking(move || run_search(&root_clone, matcher, None, true, false, extra_ignores, DEFAULT_MAX_ARCHIVE_ENTRY_BYTES, cancel, tx));
+
+        // An already-cancelled token means the walk bails before yielding anything; once the
+        // sole sender is dropped, the receiver sees the channel close rather than any result.
+        assert!(matches!(rx.recv().await, Err(broadcast::error::RecvError::Closed)));
+    }
+
+    #[test]
+    fn test_security_header_values_set_expected_policy() {
+        let headers = security_header_values();
+        let get = |name: &header::HeaderName| headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.clone());
+
+        assert_eq!(get(&header::X_CONTENT_TYPE_OPTIONS), Some(HeaderValue::from_static("nosniff")));
+        assert_eq!(get(&header::X_FRAME_OPTIONS), Some(HeaderValue::from_static("SAMEORIGIN")));
+        assert_eq!(get(&header::REFERRER_POLICY), Some(HeaderValue::from_static("same-origin")));
+        let csp = get(&header::CONTENT_SECURITY_POLICY).unwrap();
+        assert!(csp.to_str().unwrap().contains("default-src 'self'"));
+    }
+
+    #[test]
+    fn test_parse_allowed_origins_drops_unparseable_entries() {
+        let origins = parse_allowed_origins(&[
+            "https://example.com".to_string(),
+            "not a valid header value\n".to_string(),
+        ]);
+        assert_eq!(origins, vec![HeaderValue::from_static("https://example.com")]);
+    }
+}